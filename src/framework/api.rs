@@ -1,3 +1,6 @@
+use std::sync::Arc;
+use std::old_io::TempDir;
+
 use collections;
 use serialize::json;
 
@@ -14,6 +17,10 @@ use framework::nesting::{self, Nesting, Node};
 use framework::media;
 use framework::formatters;
 use framework::path;
+use framework::multipart;
+use framework::negotiation;
+use framework::cors;
+use framework::cookies;
 
 #[allow(dead_code)]
 #[allow(missing_copy_implementations)]
@@ -42,6 +49,8 @@ pub struct Api {
     default_error_formatters: framework::ErrorFormatters,
     consumes: Option<Vec<mime::Mime>>,
     produces: Option<Vec<mime::Mime>>,
+    cors: Option<Arc<cors::Cors>>,
+    secret_key: Option<Vec<u8>>,
 }
 
 unsafe impl Send for Api {}
@@ -61,6 +70,8 @@ impl Api {
             default_error_formatters: vec![formatters::create_default_error_formatter()],
             consumes: None,
             produces: None,
+            cors: None,
+            secret_key: None,
         }
     }
 
@@ -90,6 +101,20 @@ impl Api {
         self.produces = Some(mimes);
     }
 
+    pub fn secret_key(&mut self, key: &[u8]) {
+        self.secret_key = Some(key.to_vec());
+    }
+
+    pub fn cors(&mut self, cors: cors::Cors) {
+        let cors = Arc::new(cors);
+        self.cors = Some(cors.clone());
+
+        self.after.push(Box::new(move |client: &mut framework::Client, _params: &mut json::Object| {
+            cors.apply(client.request(), client.response_mut());
+            Ok(())
+        }));
+    }
+
     pub fn error_formatter<F>(&mut self, formatter: F) 
     where F: Fn(&Box<Error + 'static>, &media::Media) -> Option<backend::Response> + Send+Sync {
         self.error_formatters.push(Box::new(formatter));
@@ -114,14 +139,14 @@ impl Api {
         
     }
 
-    fn extract_media(&self, req: &backend::Request) -> Option<media::Media> {
-        let header = req.headers().get::<header::Accept>();
-        match header {
-            Some(&header::Accept(ref mimes)) if !mimes.is_empty() => {
-                // TODO: Allow only several mime types
-                Some(media::Media::from_mime(&mimes[0].item))
-            },
-            _ => Some(media::Media::default())
+    fn extract_media(&self, req: &backend::Request) -> Result<media::Media, Box<Error>> {
+        let accept = req.headers().get::<header::Accept>();
+        let produces = self.produces.as_ref().map(|m| m.as_slice()).unwrap_or(&[]);
+
+        match negotiation::negotiate_produces(accept, produces) {
+            Some(mime) => Ok(media::Media::from_mime(&mime)),
+            None if produces.is_empty() => Ok(media::Media::default()),
+            None => Err(Box::new(errors::NotAcceptable) as Box<Error>)
         }
     }
 
@@ -195,16 +220,36 @@ impl Api {
                     }
                 },
                 Err(_) => return Err(Box::new(errors::Body::new(format!("Invalid encoded data"))) as Box<Error>)
-            }  
+            }
         }
 
         Ok(())
     }
 
-    fn parse_request(req: &mut backend::Request, params: &mut json::Object) -> backend::HandleSuccessResult {
+    fn parse_multipart_body(req: &mut backend::Request, params: &mut json::Object)
+    -> backend::HandleResult<(multipart::FileParts, Option<TempDir>)> {
+        let boundary = match req.headers().get::<header::ContentType>() {
+            Some(&header::ContentType(ref mime)) => multipart::find_boundary(mime),
+            None => None
+        };
+
+        let boundary = match boundary {
+            Some(boundary) => boundary,
+            None => return Err(Box::new(errors::Body::new("Missing multipart boundary".to_string())) as Box<Error>)
+        };
+
+        let bytes = try!(req.body_mut().read_to_end().map_err(|_|
+            Box::new(errors::Body::new("Invalid request body".to_string())) as Box<Error>
+        ));
+
+        multipart::parse(bytes.as_slice(), boundary.as_slice(), params)
+    }
+
+    fn parse_request(req: &mut backend::Request, params: &mut json::Object)
+    -> backend::HandleResult<(multipart::FileParts, Option<TempDir>)> {
         // extend params with query-string params if any
         if req.url().query().is_some() {
-            try!(Api::parse_query(req.url().query().as_ref().unwrap().as_slice(), params));   
+            try!(Api::parse_query(req.url().query().as_ref().unwrap().as_slice(), params));
         }
 
         // extend params with json-encoded body params if any
@@ -212,28 +257,38 @@ impl Api {
             try!(Api::parse_json_body(req, params));
         } else if req.is_urlencoded_body() {
             try!(Api::parse_urlencoded_body(req, params));
+        } else if req.is_multipart_body() {
+            return Api::parse_multipart_body(req, params);
         }
 
-        Ok(())
+        Ok((vec![], None))
     }
 
     #[allow(unused_variables)]
-    pub fn call<'a>(&self, 
-        rest_path: &str, 
-        req: &'a mut (backend::Request + 'a), 
+    pub fn call<'a>(&self,
+        rest_path: &str,
+        req: &'a mut (backend::Request + 'a),
         app: &app::Application) -> backend::HandleExtendedResult<backend::Response> {
-        
+
         let mut params = collections::BTreeMap::new();
         let parse_result = Api::parse_request(req, &mut params);
 
-        let api_result = parse_result.and_then(|_| {
-            self.api_call(rest_path, &mut params, req, &mut framework::CallInfo::new(app))
+        let api_result = parse_result.and_then(|(file_parts, upload_dir)| {
+            let mut info = framework::CallInfo::new(app);
+            info.file_parts = file_parts;
+            // Owned by `info` for the rest of the request; its `Drop` impl
+            // reclaims the spooled upload directory once `info` goes out of
+            // scope below, instead of leaking it permanently.
+            info.upload_dir = upload_dir;
+            info.cookies = cookies::CookieJar::from_header(req.headers().get::<header::Cookie>());
+            info.secret_key = self.secret_key.clone();
+            self.api_call(rest_path, &mut params, req, &mut info)
         });
         
         match api_result {
             Ok(resp) => Ok(resp),
             Err(err) => {
-                let resp = self.handle_error(&err, &self.extract_media(req).unwrap_or_else(|| media::Media::default()));
+                let resp = self.handle_error(&err, &self.extract_media(req).unwrap_or_else(|_| media::Media::default()));
                 Err(backend::ErrorResponse { 
                     error: err,
                     response: resp 
@@ -265,6 +320,13 @@ impl framework::ApiHandler for Api {
             None => rest_path
         };
 
+        // CORS preflight short-circuits before any endpoint routing happens.
+        if let Some(ref cors) = self.cors {
+            if let Some(resp) = cors.preflight_response(req) {
+                return Ok(resp);
+            }
+        }
+
         let mut media: Option<media::Media> = None;
 
         // Check version
@@ -318,16 +380,30 @@ impl framework::ApiHandler for Api {
             }
         }
 
-        // Check accept media type
+        // Check accept media type. This is deliberately best-effort rather than a hard
+        // 406/415 failure: a nested `Endpoint` may declare its own `produces`/`consumes`
+        // that should take precedence over ours, and we don't know which endpoint will
+        // match until routing descends further. `Endpoint::negotiate` does the real,
+        // final enforcement once a specific endpoint has matched; `info.produces`/
+        // `info.consumes` below are what it falls back to when the endpoint has none
+        // of its own.
         if media.is_none() {
-            match self.extract_media(req) {
-                Some(media) => {
-                    info.media = media
-                },
-                None => return Err(Box::new(errors::NotAcceptable) as Box<Error>)
-            }
+            info.media = match self.extract_media(req) {
+                Ok(media) => media,
+                Err(_) => media::Media::default()
+            };
+        } else {
+            // `Versioning::AcceptHeader` already matched this request's Accept
+            // entry against a vendor mime, not a plain `produces` mime, so
+            // `Endpoint::negotiate` must not re-run produces negotiation
+            // against the same raw header.
+            info.media = media.unwrap();
+            info.media_resolved = true;
         }
 
+        info.produces = self.produces.clone();
+        info.consumes = self.consumes.clone();
+
         self.push_node(info);
         self.call_handlers(rest_path, params, req, info)
     }
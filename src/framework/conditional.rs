@@ -0,0 +1,131 @@
+use time;
+
+use server::method::Method;
+use backend;
+
+/// Parses an HTTP-date in any of the three forms RFC 7231 allows on input.
+fn parse_http_date(value: &str) -> Option<time::Tm> {
+    time::strptime(value, "%a, %d %b %Y %H:%M:%S %Z")
+        .or_else(|_| time::strptime(value, "%A, %d-%b-%y %H:%M:%S %Z"))
+        .or_else(|_| time::strptime(value, "%c"))
+        .ok()
+}
+
+/// Outcome of evaluating a request's conditional headers against an
+/// endpoint's `ETag`/`Last-Modified` validators.
+pub enum Conditional {
+    Proceed,
+    NotModified,
+    PreconditionFailed,
+}
+
+fn header_value(req: &backend::Request, name: &str) -> Option<String> {
+    req.headers().get_raw(name)
+        .and_then(|lines| lines.first())
+        .and_then(|line| String::from_utf8(line.clone()).ok())
+}
+
+fn parse_etag(tag: &str) -> (bool, &str) {
+    let tag = tag.trim();
+    if tag.starts_with("W/") {
+        (true, tag[2..].trim())
+    } else {
+        (false, tag)
+    }
+}
+
+fn split_etag_list(header_value: &str) -> Vec<&str> {
+    header_value.split(',').map(|tag| tag.trim()).filter(|tag| tag.len() > 0).collect()
+}
+
+/// Weak comparison: opaque tags match regardless of the `W/` prefix.
+fn weak_matches(a: &str, b: &str) -> bool {
+    parse_etag(a).1 == parse_etag(b).1
+}
+
+/// Strong comparison: both sides must be strong validators with the same tag.
+fn strong_matches(a: &str, b: &str) -> bool {
+    let (a_weak, a_tag) = parse_etag(a);
+    let (b_weak, b_tag) = parse_etag(b);
+    !a_weak && !b_weak && a_tag == b_tag
+}
+
+fn if_none_match_satisfied(req: &backend::Request, etag: &str) -> bool {
+    match header_value(req, "If-None-Match") {
+        Some(ref value) if value.as_slice() == "*" => true,
+        Some(ref value) => split_etag_list(value.as_slice()).iter().any(|candidate| weak_matches(candidate, etag)),
+        None => false
+    }
+}
+
+fn if_match_satisfied(req: &backend::Request, etag: &str) -> bool {
+    match header_value(req, "If-Match") {
+        Some(ref value) if value.as_slice() == "*" => true,
+        Some(ref value) => split_etag_list(value.as_slice()).iter().any(|candidate| strong_matches(candidate, etag)),
+        None => true
+    }
+}
+
+fn if_modified_since_satisfied(req: &backend::Request, last_modified: &str) -> bool {
+    match header_value(req, "If-Modified-Since") {
+        // Compare parsed instants, not raw strings: HTTP-dates start with a
+        // weekday name, so string comparison isn't chronological.
+        Some(ref since) => match (parse_http_date(last_modified), parse_http_date(since.as_slice())) {
+            (Some(last_modified), Some(since)) => last_modified.to_timespec() <= since.to_timespec(),
+            _ => false
+        },
+        None => false
+    }
+}
+
+/// Compares a handler-set `ETag`/`Last-Modified` pair against the request's
+/// conditional headers. `If-None-Match` takes precedence over `If-Modified-Since`.
+pub fn evaluate(req: &backend::Request, etag: Option<&str>, last_modified: Option<&str>) -> Conditional {
+    let is_safe_method = req.method() == &Method::Get || req.method() == &Method::Head;
+
+    if is_safe_method {
+        if header_value(req, "If-None-Match").is_some() {
+            let matched = match etag {
+                Some(etag) => if_none_match_satisfied(req, etag),
+                None => false
+            };
+            return if matched { Conditional::NotModified } else { Conditional::Proceed };
+        }
+
+        if let Some(last_modified) = last_modified {
+            if if_modified_since_satisfied(req, last_modified) {
+                return Conditional::NotModified;
+            }
+        }
+
+        Conditional::Proceed
+    } else {
+        match etag {
+            Some(etag) if !if_match_satisfied(req, etag) => Conditional::PreconditionFailed,
+            _ => Conditional::Proceed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_http_date, weak_matches, strong_matches};
+
+    #[test]
+    fn weak_and_strong_etag_comparison() {
+        assert!(weak_matches("W/\"v1\"", "\"v1\""));
+        assert!(!weak_matches("\"v1\"", "\"v2\""));
+
+        assert!(strong_matches("\"v1\"", "\"v1\""));
+        assert!(!strong_matches("W/\"v1\"", "\"v1\""));
+    }
+
+    #[test]
+    fn http_dates_compare_chronologically_not_lexicographically() {
+        let earlier = parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        let later = parse_http_date("Thu, 22 Oct 2015 07:28:00 GMT").unwrap();
+
+        assert!(earlier.to_timespec() < later.to_timespec());
+        assert!(parse_http_date("not a date").is_none());
+    }
+}
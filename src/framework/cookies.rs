@@ -0,0 +1,242 @@
+use collections;
+
+use crypto::hmac::Hmac;
+use crypto::sha2::Sha256;
+use crypto::mac::Mac;
+use serialize::hex::ToHex;
+
+use server::header;
+use backend;
+
+/// Parsed view of the incoming `Cookie` header.
+pub struct CookieJar {
+    cookies: collections::BTreeMap<String, String>,
+}
+
+impl CookieJar {
+
+    pub fn from_header(header: Option<&header::Cookie>) -> CookieJar {
+        let mut cookies = collections::BTreeMap::new();
+
+        if let Some(&header::Cookie(ref pairs)) = header {
+            for pair in pairs.iter() {
+                let mut split = pair.as_slice().splitn(2, '=');
+                let name = split.next().unwrap_or("").trim().to_string();
+                let value = split.next().unwrap_or("").trim().to_string();
+
+                if name.len() > 0 {
+                    cookies.insert(name, value);
+                }
+            }
+        }
+
+        CookieJar { cookies: cookies }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.cookies.get(name).map(|value| value.as_slice())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.cookies.contains_key(name)
+    }
+
+    /// Wraps this jar with a secret key so signed cookies can be verified.
+    pub fn signed<'a>(&'a self, key: &'a [u8]) -> SignedCookieJar<'a> {
+        SignedCookieJar { jar: self, key: key }
+    }
+}
+
+/// A `CookieJar` paired with a secret key for HMAC-signed cookie values.
+pub struct SignedCookieJar<'a> {
+    jar: &'a CookieJar,
+    key: &'a [u8],
+}
+
+impl<'a> SignedCookieJar<'a> {
+
+    /// Returns the cookie's value if present and its signature is valid.
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.jar.get(name).and_then(|raw| verify(raw, self.key))
+    }
+
+    /// Signs `value`, producing the string that should be stored in the cookie.
+    pub fn sign(&self, value: &str) -> String {
+        sign(value, self.key)
+    }
+}
+
+fn hmac_hex(value: &str, key: &[u8]) -> String {
+    let mut hmac = Hmac::new(Sha256::new(), key);
+    hmac.input(value.as_bytes());
+    hmac.result().code().to_hex()
+}
+
+fn sign(value: &str, key: &[u8]) -> String {
+    format!("{}|{}", value, hmac_hex(value, key))
+}
+
+fn verify(raw: &str, key: &[u8]) -> Option<String> {
+    let split_at = match raw.rfind('|') {
+        Some(pos) => pos,
+        None => return None
+    };
+
+    let (value, signature) = (&raw[..split_at], &raw[(split_at + 1)..]);
+
+    if signature == hmac_hex(value, key).as_slice() {
+        Some(value.to_string())
+    } else {
+        None
+    }
+}
+
+#[derive(Clone)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+/// Builder for a single `Set-Cookie` response header.
+pub struct SetCookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<u32>,
+    expires: Option<String>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl SetCookie {
+
+    pub fn new(name: &str, value: &str) -> SetCookie {
+        SetCookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    /// Signs `value` with `key` before storing it as the cookie's value.
+    pub fn signed(name: &str, value: &str, key: &[u8]) -> SetCookie {
+        SetCookie::new(name, sign(value, key).as_slice())
+    }
+
+    pub fn path(mut self, path: &str) -> SetCookie {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub fn domain(mut self, domain: &str) -> SetCookie {
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    pub fn max_age(mut self, seconds: u32) -> SetCookie {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    pub fn expires(mut self, http_date: &str) -> SetCookie {
+        self.expires = Some(http_date.to_string());
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> SetCookie {
+        self.secure = secure;
+        self
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> SetCookie {
+        self.http_only = http_only;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> SetCookie {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    pub fn to_header_value(&self) -> String {
+        let mut value = format!("{}={}", self.name, self.value);
+
+        if let Some(ref path) = self.path {
+            value.push_str(format!("; Path={}", path).as_slice());
+        }
+
+        if let Some(ref domain) = self.domain {
+            value.push_str(format!("; Domain={}", domain).as_slice());
+        }
+
+        if let Some(max_age) = self.max_age {
+            value.push_str(format!("; Max-Age={}", max_age).as_slice());
+        }
+
+        if let Some(ref expires) = self.expires {
+            value.push_str(format!("; Expires={}", expires).as_slice());
+        }
+
+        if self.secure {
+            value.push_str("; Secure");
+        }
+
+        if self.http_only {
+            value.push_str("; HttpOnly");
+        }
+
+        match self.same_site {
+            Some(SameSite::Strict) => value.push_str("; SameSite=Strict"),
+            Some(SameSite::Lax) => value.push_str("; SameSite=Lax"),
+            Some(SameSite::None) => value.push_str("; SameSite=None"),
+            None => ()
+        }
+
+        value
+    }
+
+    /// Appends this cookie as its own `Set-Cookie` header on `response`.
+    pub fn append_to(&self, response: &mut backend::Response) {
+        response.headers_mut().append_raw("Set-Cookie", self.to_header_value().into_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use server::header;
+
+    use super::{CookieJar, SetCookie};
+
+    #[test]
+    fn parses_pairs_splitting_on_first_equals_only() {
+        let jar = CookieJar::from_header(Some(&header::Cookie(vec![
+            "token=a=b=c".to_string(), "b=2".to_string()
+        ])));
+
+        assert_eq!(jar.get("token").unwrap(), "a=b=c");
+        assert_eq!(jar.get("b").unwrap(), "2");
+        assert!(!jar.contains("c"));
+    }
+
+    #[test]
+    fn signed_round_trips_and_rejects_tampering() {
+        let key = b"s3cr3t";
+        let header_value = SetCookie::signed("session", "user-1", key).to_header_value();
+        let raw = header_value.as_slice().splitn(2, '=').nth(1).unwrap().to_string();
+
+        let jar = CookieJar::from_header(Some(&header::Cookie(vec![format!("session={}", raw)])));
+        assert_eq!(jar.signed(key).get("session").unwrap(), "user-1");
+
+        let tampered = CookieJar::from_header(Some(&header::Cookie(vec!["session=user-1|deadbeef".to_string()])));
+        assert!(tampered.signed(key).get("session").is_none());
+    }
+}
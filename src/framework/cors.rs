@@ -0,0 +1,210 @@
+use server::method::Method;
+use server::status;
+
+use backend;
+
+/// Which origins a `Cors` configuration allows.
+pub enum Origins {
+    Any,
+    List(Vec<String>),
+    Predicate(Box<Fn(&str) -> bool + Send + Sync>),
+}
+
+/// CORS configuration attached to an `Api` via `Api::cors(..)`.
+pub struct Cors {
+    origins: Origins,
+    methods: Vec<Method>,
+    headers: Vec<String>,
+    exposed_headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<u32>,
+}
+
+impl Cors {
+
+    pub fn new() -> Cors {
+        Cors {
+            origins: Origins::List(vec![]),
+            methods: vec![Method::Get, Method::Post, Method::Put, Method::Delete, Method::Patch],
+            headers: vec![],
+            exposed_headers: vec![],
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    pub fn build<F>(builder: F) -> Cors where F: FnOnce(&mut Cors) {
+        let mut cors = Cors::new();
+        builder(&mut cors);
+
+        // Reflecting any origin while also allowing credentialed requests
+        // defeats the same-origin protection CORS exists to provide.
+        if let Origins::Any = cors.origins {
+            assert!(!cors.credentials, "Cors: any_origin() cannot be combined with credentials(true)");
+        }
+
+        cors
+    }
+
+    pub fn origins(&mut self, origins: Vec<String>) {
+        self.origins = Origins::List(origins);
+    }
+
+    pub fn any_origin(&mut self) {
+        self.origins = Origins::Any;
+    }
+
+    pub fn origin_predicate<F>(&mut self, predicate: F) where F: Fn(&str) -> bool + Send + Sync + 'static {
+        self.origins = Origins::Predicate(Box::new(predicate));
+    }
+
+    pub fn methods(&mut self, methods: Vec<Method>) {
+        self.methods = methods;
+    }
+
+    pub fn headers(&mut self, headers: Vec<String>) {
+        self.headers = headers;
+    }
+
+    pub fn expose_headers(&mut self, headers: Vec<String>) {
+        self.exposed_headers = headers;
+    }
+
+    pub fn credentials(&mut self, allow: bool) {
+        self.credentials = allow;
+    }
+
+    pub fn max_age(&mut self, seconds: u32) {
+        self.max_age = Some(seconds);
+    }
+
+    // The single origin value to reflect back for a given request `Origin`,
+    // or `None` if that origin is not allowed. Never returns a blanket "*"
+    // unless the wildcard was explicitly configured and credentials are off.
+    fn allowed_origin(&self, origin: &str) -> Option<String> {
+        let matches = match self.origins {
+            Origins::Any => true,
+            Origins::List(ref list) => list.iter().any(|allowed| allowed.as_slice() == origin),
+            Origins::Predicate(ref predicate) => predicate(origin)
+        };
+
+        if !matches {
+            return None;
+        }
+
+        if let Origins::Any = self.origins {
+            if !self.credentials {
+                return Some("*".to_string());
+            }
+        }
+
+        Some(origin.to_string())
+    }
+
+    fn request_origin(req: &backend::Request) -> Option<String> {
+        req.headers().get_raw("Origin").and_then(|lines| lines.first())
+            .and_then(|line| String::from_utf8(line.clone()).ok())
+    }
+
+    // Sets `Access-Control-Allow-Origin`/`Vary` on an already-built response
+    // for a normal (non-preflight) request. Called as an `after` callback.
+    pub fn apply(&self, req: &backend::Request, resp: &mut backend::Response) {
+        let origin = match Cors::request_origin(req) {
+            Some(origin) => origin,
+            None => return
+        };
+
+        let allowed = match self.allowed_origin(origin.as_slice()) {
+            Some(allowed) => allowed,
+            None => return
+        };
+
+        let headers = resp.headers_mut();
+        headers.set_raw("Access-Control-Allow-Origin", vec![allowed.into_bytes()]);
+        headers.set_raw("Vary", vec![b"Origin".to_vec()]);
+
+        if self.credentials {
+            headers.set_raw("Access-Control-Allow-Credentials", vec![b"true".to_vec()]);
+        }
+
+        if !self.exposed_headers.is_empty() {
+            headers.set_raw("Access-Control-Expose-Headers", vec![self.exposed_headers.connect(", ").into_bytes()]);
+        }
+    }
+
+    /// Is this an `OPTIONS` preflight request this `Cors` should answer directly?
+    pub fn is_preflight(&self, req: &backend::Request) -> bool {
+        req.method() == &Method::Options &&
+        Cors::request_origin(req).is_some() &&
+        req.headers().get_raw("Access-Control-Request-Method").is_some()
+    }
+
+    /// Builds the 204 response for a preflight request whose origin, requested
+    /// method and requested headers are all allowed; returns `None` for any
+    /// other request, including a preflight that fails one of those checks.
+    pub fn preflight_response(&self, req: &backend::Request) -> Option<backend::Response> {
+        if !self.is_preflight(req) {
+            return None;
+        }
+
+        let origin = match Cors::request_origin(req) {
+            Some(origin) => origin,
+            None => return None
+        };
+
+        let allowed_origin = match self.allowed_origin(origin.as_slice()) {
+            Some(allowed) => allowed,
+            None => return None
+        };
+
+        let requested_method = req.headers().get_raw("Access-Control-Request-Method")
+            .and_then(|lines| lines.first())
+            .and_then(|line| String::from_utf8(line.clone()).ok());
+
+        let method_allowed = match requested_method {
+            Some(ref requested) => self.methods.iter().any(|m| m.to_string().as_slice() == requested.as_slice()),
+            None => false
+        };
+
+        if !method_allowed {
+            return None;
+        }
+
+        let requested_headers = req.headers().get_raw("Access-Control-Request-Headers")
+            .and_then(|lines| lines.first())
+            .and_then(|line| String::from_utf8(line.clone()).ok());
+
+        if let Some(requested_headers) = requested_headers {
+            let all_allowed = requested_headers.split(',').map(|h| h.trim()).filter(|h| h.len() > 0)
+                .all(|requested| self.headers.iter().any(|allowed| allowed.as_slice().eq_ignore_ascii_case(requested)));
+
+            if !all_allowed {
+                return None;
+            }
+        }
+
+        let mut resp = backend::Response::new(status::StatusCode::NoContent);
+        {
+            let headers = resp.headers_mut();
+            headers.set_raw("Access-Control-Allow-Origin", vec![allowed_origin.into_bytes()]);
+            headers.set_raw("Vary", vec![b"Origin".to_vec()]);
+            headers.set_raw("Access-Control-Allow-Methods", vec![
+                self.methods.iter().map(|m| m.to_string()).collect::<Vec<_>>().connect(", ").into_bytes()
+            ]);
+
+            if !self.headers.is_empty() {
+                headers.set_raw("Access-Control-Allow-Headers", vec![self.headers.connect(", ").into_bytes()]);
+            }
+
+            if self.credentials {
+                headers.set_raw("Access-Control-Allow-Credentials", vec![b"true".to_vec()]);
+            }
+
+            if let Some(max_age) = self.max_age {
+                headers.set_raw("Access-Control-Max-Age", vec![max_age.to_string().into_bytes()]);
+            }
+        }
+
+        Some(resp)
+    }
+}
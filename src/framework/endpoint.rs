@@ -1,13 +1,18 @@
-use serialize::json;
+use serialize::{json, Decodable};
 
 use valico;
 
 use server::method;
 use server::mime;
+use server::header;
+use server::status;
 use backend;
-use errors;
+use errors::{self, Error};
 use framework;
 use framework::path;
+use framework::media;
+use framework::negotiation;
+use framework::conditional::{self, Conditional};
 
 pub type EndpointHandler = Box<for<'a> Fn(framework::Client<'a>, &json::Object) -> backend::HandleResult<framework::Client<'a>> + 'static + Sync>;
 
@@ -85,6 +90,20 @@ impl Endpoint {
         EndpointHandlerPresent::HandlerPresent
     }
 
+    /// Like `handle`, but the handler receives `T` decoded from the merged
+    /// (query + body + path capture) params instead of the raw `json::Object`.
+    /// Run after `validate` so `T`'s shape can rely on the coercer schema
+    /// having already run.
+    pub fn handle_typed<T, F>(&mut self, handler: F) -> EndpointHandlerPresent
+    where T: FromRequest,
+          F: for<'a> Fn(framework::Client<'a>, T) -> backend::HandleResult<framework::Client<'a>> + 'static + Sync+Send {
+        self.handler = Some(Box::new(move |client, params| {
+            let typed = try!(T::from_request(params));
+            handler(client, typed)
+        }));
+        EndpointHandlerPresent::HandlerPresent
+    }
+
     fn validate(&self, params: &mut json::Object) -> backend::HandleResult<()> {
         // Validate namespace params with valico
         if self.coercer.is_some() {
@@ -102,7 +121,9 @@ impl Endpoint {
     pub fn call_decode<'a>(&self, params: &mut json::Object, req: &'a mut (backend::Request + 'a), 
                        info: &mut framework::CallInfo) -> backend::HandleResult<backend::Response> {
         
-        let mut client = framework::Client::new(info.app, self, req, &info.media);
+        // `info.file_parts`/`info.cookies` are populated by `Api::call`; forward them onto
+        // `Client` so handlers can reach them via `client.file_parts()`/`client.cookies()`.
+        let mut client = framework::Client::new(info.app, self, req, &info.media, &info.file_parts, &info.cookies);
 
         for parent in info.parents.iter() {
             try!(Endpoint::call_callbacks(parent.get_before(), &mut client, params));
@@ -125,10 +146,54 @@ impl Endpoint {
             try!(Endpoint::call_callbacks(parent.get_after(), &mut client, params));
         }
 
+        Endpoint::flush_cookies(&mut client);
+        try!(Endpoint::apply_conditional(&mut client));
+
         Ok(client.move_response())
     }
 
-    fn call_callbacks(cbs: &Vec<framework::Callback>, client: &mut framework::Client, params: &mut json::Object) 
+    // Appends every `SetCookie` the handler queued via `client.set_cookie(..)`
+    // onto the response as its own `Set-Cookie` header line.
+    fn flush_cookies(client: &mut framework::Client) {
+        for cookie in client.pending_cookies().iter() {
+            cookie.append_to(client.response_mut());
+        }
+    }
+
+    // If the handler set `ETag`/`Last-Modified` on its response, compare them
+    // against the request's conditional headers and collapse the response to
+    // a bare `304 Not Modified` (or reject with `412 Precondition Failed`)
+    // when they say the client's cached copy is still valid.
+    fn apply_conditional(client: &mut framework::Client) -> backend::HandleSuccessResult {
+        let etag = client.response().headers().get_raw("ETag")
+            .and_then(|lines| lines.first())
+            .and_then(|line| String::from_utf8(line.clone()).ok());
+        let last_modified = client.response().headers().get_raw("Last-Modified")
+            .and_then(|lines| lines.first())
+            .and_then(|line| String::from_utf8(line.clone()).ok());
+
+        if etag.is_none() && last_modified.is_none() {
+            return Ok(());
+        }
+
+        let outcome = conditional::evaluate(
+            client.request(),
+            etag.as_ref().map(|s| s.as_slice()),
+            last_modified.as_ref().map(|s| s.as_slice())
+        );
+
+        match outcome {
+            Conditional::Proceed => Ok(()),
+            Conditional::NotModified => {
+                client.response_mut().set_status(status::StatusCode::NotModified);
+                client.response_mut().clear_body();
+                Ok(())
+            },
+            Conditional::PreconditionFailed => Err(Box::new(errors::PreconditionFailed) as Box<Error>)
+        }
+    }
+
+    fn call_callbacks(cbs: &Vec<framework::Callback>, client: &mut framework::Client, params: &mut json::Object)
     -> backend::HandleSuccessResult {
         for cb in cbs.iter() {
             try!(cb(client, params));
@@ -137,6 +202,58 @@ impl Endpoint {
         Ok(())
     }
 
+    // An endpoint's own `produces`/`consumes` take precedence over whatever
+    // the enclosing `Api` declared; when the endpoint has none of its own, it
+    // falls back to the `Api`'s lists (stashed on `info` by `Api::api_call`,
+    // which no longer enforces them itself so this is the sole enforcement
+    // point for both).
+    fn negotiate<'r>(&self, req: &'r (backend::Request + 'r), info: &mut framework::CallInfo) -> backend::HandleSuccessResult {
+        let produces = self.produces.as_ref().or(info.produces.as_ref());
+        let consumes = self.consumes.as_ref().or(info.consumes.as_ref());
+
+        // `info.media_resolved` means `Api::api_call` already matched this
+        // request's Accept header against a vendor mime (AcceptHeader
+        // versioning); re-negotiating a plain `produces` entry against that
+        // same header here would reject a request that already matched.
+        if !info.media_resolved {
+            if let Some(produces) = produces {
+                let accept = req.headers().get::<header::Accept>();
+                match negotiation::negotiate_produces(accept, produces.as_slice()) {
+                    Some(mime) => info.media = media::Media::from_mime(&mime),
+                    None => return Err(Box::new(errors::NotAcceptable) as Box<Error>)
+                }
+            }
+        }
+
+        if let Some(consumes) = consumes {
+            if let Some(&header::ContentType(ref mime)) = req.headers().get::<header::ContentType>() {
+                if !negotiation::validate_consumes(mime, consumes.as_slice()) {
+                    return Err(Box::new(errors::UnsupportedMediaType) as Box<Error>)
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+}
+
+/// Extracts a strongly-typed value out of an endpoint's validated params.
+/// Implement this directly for custom extraction, or rely on the blanket
+/// `Decodable` impl below to deserialize straight off `#[derive(Decodable)]`
+/// structs that mirror the endpoint's coercer schema.
+pub trait FromRequest: Sized {
+    fn from_request(params: &json::Object) -> backend::HandleResult<Self>;
+}
+
+impl<T: Decodable> FromRequest for T {
+    fn from_request(params: &json::Object) -> backend::HandleResult<T> {
+        let encoded = json::Json::Object(params.clone()).to_string();
+
+        json::decode::<T>(encoded.as_slice()).map_err(|err|
+            Box::new(errors::Validation { reason: format!("Could not decode params: {}", err) }) as Box<Error>
+        )
+    }
 }
 
 impl framework::ApiHandler for Endpoint {
@@ -154,6 +271,7 @@ impl framework::ApiHandler for Endpoint {
         match self.path.is_match(rest_path) {
             Some(captures) =>  {
                 self.path.apply_captures(params, captures);
+                try!(self.negotiate(req, info));
                 self.call_decode(params, req, info)
             },
             None => Err(Box::new(errors::NotMatch) as Box<errors::Error>)
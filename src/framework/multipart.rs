@@ -0,0 +1,282 @@
+use std::old_io::{File, TempDir};
+use std::old_io::fs::PathExtensions;
+
+use serialize::json;
+
+use server::mime;
+use errors::{self, Error};
+use backend;
+
+/// An uploaded file spooled to disk.
+#[derive(Clone)]
+pub struct FilePart {
+    pub name: String,
+    pub filename: Option<String>,
+    pub content_type: Option<mime::Mime>,
+    pub path: Path,
+    pub size: u64,
+}
+
+pub type FileParts = Vec<FilePart>;
+
+struct PartHeaders {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<mime::Mime>,
+}
+
+/// Pulls the `boundary` parameter out of a content type.
+pub fn find_boundary(content_type: &mime::Mime) -> Option<String> {
+    for &(ref attr, ref value) in content_type.2.iter() {
+        if attr.as_slice().eq_ignore_ascii_case("boundary") {
+            return Some(value.to_string());
+        }
+    }
+
+    None
+}
+
+fn split_lines(block: &[u8]) -> Vec<&[u8]> {
+    // Accept both CRLF and bare LF line endings within a part's header block.
+    let mut lines = vec![];
+    let mut start = 0;
+
+    for i in 0..block.len() {
+        if block[i] == b'\n' {
+            let mut end = i;
+            if end > start && block[end - 1] == b'\r' {
+                end -= 1;
+            }
+            lines.push(&block[start..end]);
+            start = i + 1;
+        }
+    }
+
+    // The header block handed in stops before its blank-line terminator, so
+    // its last line has no trailing newline of its own.
+    if start < block.len() {
+        lines.push(&block[start..]);
+    }
+
+    lines
+}
+
+fn parse_part_headers(block: &[u8]) -> Result<PartHeaders, Box<Error>> {
+    let mut name = None;
+    let mut filename = None;
+    let mut content_type = None;
+
+    for line in split_lines(block).into_iter() {
+        let line = match String::from_utf8(line.to_vec()) {
+            Ok(line) => line,
+            Err(_) => return Err(Box::new(errors::Body::new("Invalid UTF-8 in part headers".to_string())) as Box<Error>)
+        };
+
+        if line.len() == 0 {
+            continue;
+        }
+
+        let mut split = line.splitn(2, ':');
+        let header_name = split.next().unwrap_or("").trim().to_string();
+        let header_value = split.next().unwrap_or("").trim().to_string();
+
+        if header_name.as_slice().eq_ignore_ascii_case("Content-Disposition") {
+            for param in split_params(header_value.as_slice()).into_iter().skip(1) {
+                let param = param.trim();
+                if let Some(eq) = param.find('=') {
+                    let key = &param[..eq];
+                    let value = unquote(&param[(eq + 1)..]);
+                    if key == "name" {
+                        name = Some(value);
+                    } else if key == "filename" {
+                        filename = Some(value);
+                    }
+                }
+            }
+        } else if header_name.as_slice().eq_ignore_ascii_case("Content-Type") {
+            content_type = header_value.as_slice().parse::<mime::Mime>().ok();
+        }
+    }
+
+    match name {
+        Some(name) => Ok(PartHeaders { name: name, filename: filename, content_type: content_type }),
+        None => Err(Box::new(errors::Body::new("Multipart part is missing a name".to_string())) as Box<Error>)
+    }
+}
+
+/// Splits on `;`, ignoring `;` inside a quoted value.
+fn split_params(value: &str) -> Vec<&str> {
+    let mut params = vec![];
+    let mut start = 0;
+    let mut in_quotes = false;
+
+    for (i, c) in value.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => {
+                params.push(&value[start..i]);
+                start = i + 1;
+            },
+            _ => ()
+        }
+    }
+    params.push(&value[start..]);
+
+    params
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        value[1..(value.len() - 1)].to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    if needle.len() == 0 || from >= haystack.len() {
+        return None;
+    }
+
+    let mut i = from;
+    while i + needle.len() <= haystack.len() {
+        if &haystack[i..(i + needle.len())] == needle {
+            return Some(i);
+        }
+        i += 1;
+    }
+
+    None
+}
+
+/// Parses a `multipart/form-data` body into `params` and spooled file parts.
+/// The caller owns the returned `TempDir`, if any, for as long as the file
+/// parts need to stay on disk.
+pub fn parse(body: &[u8], boundary: &str, params: &mut json::Object) -> Result<(FileParts, Option<TempDir>), Box<Error>> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let closing_delimiter = format!("--{}--", boundary).into_bytes();
+
+    let mut file_parts = vec![];
+    let mut upload_dir: Option<TempDir> = None;
+
+    let mut pos = match find(body, delimiter.as_slice(), 0) {
+        Some(pos) => pos + delimiter.len(),
+        None => return Err(Box::new(errors::Body::new("Missing multipart boundary".to_string())) as Box<Error>)
+    };
+
+    loop {
+        // Skip the CRLF/LF that follows the boundary line itself.
+        while pos < body.len() && (body[pos] == b'\r' || body[pos] == b'\n') {
+            pos += 1;
+        }
+
+        let next_delimiter = match find(body, delimiter.as_slice(), pos) {
+            Some(next) => next,
+            None => break
+        };
+
+        let part = &body[pos..next_delimiter];
+
+        let header_end = find(part, b"\r\n\r\n", 0).map(|i| (i, i + 4))
+            .or_else(|| find(part, b"\n\n", 0).map(|i| (i, i + 2)));
+
+        if let Some((header_end, content_start)) = header_end {
+            let headers = try!(parse_part_headers(&part[..header_end]));
+            let mut content = &part[content_start..];
+
+            // Trim the trailing CRLF/LF that precedes the next boundary delimiter.
+            if content.ends_with(b"\r\n") {
+                content = &content[..(content.len() - 2)];
+            } else if content.ends_with(b"\n") {
+                content = &content[..(content.len() - 1)];
+            }
+
+            match headers.filename {
+                Some(ref filename) => {
+                    if upload_dir.is_none() {
+                        upload_dir = Some(try!(TempDir::new("rustless-multipart").map_err(|_|
+                            Box::new(errors::Body::new("Could not create temp dir for upload".to_string())) as Box<Error>
+                        )));
+                    }
+
+                    let path = upload_dir.as_ref().unwrap().path().join(format!("upload-{}", file_parts.len()));
+                    let mut file = try!(File::create(&path).map_err(|_|
+                        Box::new(errors::Body::new("Could not spool uploaded file".to_string())) as Box<Error>
+                    ));
+                    try!(file.write_all(content).map_err(|_|
+                        Box::new(errors::Body::new("Could not write uploaded file".to_string())) as Box<Error>
+                    ));
+
+                    file_parts.push(FilePart {
+                        name: headers.name,
+                        filename: Some(filename.clone()),
+                        content_type: headers.content_type,
+                        path: path,
+                        size: content.len() as u64,
+                    });
+                },
+                None => {
+                    let value = match String::from_utf8(content.to_vec()) {
+                        Ok(value) => value,
+                        Err(_) => return Err(Box::new(errors::Body::new("Invalid UTF-8 in multipart field".to_string())) as Box<Error>)
+                    };
+
+                    if !params.contains_key(&headers.name) {
+                        params.insert(headers.name, json::Json::String(value));
+                    }
+                }
+            }
+        }
+
+        if next_delimiter + closing_delimiter.len() <= body.len() &&
+           &body[next_delimiter..(next_delimiter + closing_delimiter.len())] == closing_delimiter.as_slice() {
+            break;
+        }
+
+        pos = next_delimiter + delimiter.len();
+    }
+
+    Ok((file_parts, upload_dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use serialize::json;
+
+    use super::parse;
+
+    #[test]
+    fn parses_fields_and_file_parts() {
+        let body = "--boundary\n\
+                     Content-Disposition: form-data; name=title\n\
+                     \n\
+                     Hello\n\
+                     --boundary\r\n\
+                     Content-Disposition: form-data; name=\"upload\"; filename=\"a;b.txt\"\r\n\
+                     Content-Type: text/plain\r\n\
+                     \r\n\
+                     contents\r\n\
+                     --boundary--\r\n".to_string().into_bytes();
+
+        let mut params: BTreeMap<String, json::Json> = BTreeMap::new();
+        let (file_parts, _dir) = parse(body.as_slice(), "boundary", &mut params).unwrap();
+
+        assert_eq!(params.get("title").unwrap().as_string().unwrap(), "Hello");
+        assert_eq!(file_parts[0].filename.as_ref().unwrap().as_slice(), "a;b.txt");
+        assert_eq!(file_parts[0].size, 8);
+    }
+
+    #[test]
+    fn rejects_part_without_a_name() {
+        let body = "--boundary\r\n\
+                     Content-Type: text/plain\r\n\
+                     \r\n\
+                     contents\r\n\
+                     --boundary--\r\n".to_string().into_bytes();
+
+        let mut params: BTreeMap<String, json::Json> = BTreeMap::new();
+        assert!(parse(body.as_slice(), "boundary", &mut params).is_err());
+    }
+}
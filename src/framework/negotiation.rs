@@ -0,0 +1,135 @@
+use server::mime::{Mime, TopLevel, SubLevel};
+use server::header;
+
+/// How specifically an `Accept` entry matches a candidate mime: exact beats
+/// `type/*` beats `*/*`.
+fn specificity(accept: &Mime, candidate: &Mime) -> Option<u8> {
+    let &Mime(ref accept_top, ref accept_sub, _) = accept;
+    let &Mime(ref candidate_top, ref candidate_sub, _) = candidate;
+
+    if *accept_top == TopLevel::Star {
+        return Some(0);
+    }
+
+    if accept_top != candidate_top {
+        return None;
+    }
+
+    if *accept_sub == SubLevel::Star {
+        Some(1)
+    } else if accept_sub == candidate_sub {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Does `accept` match `candidate`, honouring `type/*` and `*/*` wildcards?
+fn mime_matches(accept: &Mime, candidate: &Mime) -> bool {
+    specificity(accept, candidate).is_some()
+}
+
+/// The `Accept` entry that most specifically matches `candidate`.
+fn best_match<'a>(accept: &'a [header::QualityItem<Mime>], candidate: &Mime) -> Option<&'a header::QualityItem<Mime>> {
+    let mut best: Option<(&header::QualityItem<Mime>, u8)> = None;
+
+    for quality_item in accept.iter() {
+        if let Some(specificity) = specificity(&quality_item.item, candidate) {
+            let better = match best {
+                Some((_, best_specificity)) => specificity > best_specificity,
+                None => true
+            };
+
+            if better {
+                best = Some((quality_item, specificity));
+            }
+        }
+    }
+
+    best.map(|(quality_item, _)| quality_item)
+}
+
+/// Picks the best `produces` entry for the given `Accept` header, ranking
+/// candidates by the quality of their most specific matching entry so an
+/// exact-type `q=0` always rejects regardless of a matching wildcard's `q`.
+pub fn negotiate_produces(accept: Option<&header::Accept>, produces: &[Mime]) -> Option<Mime> {
+    if produces.is_empty() {
+        return match accept {
+            Some(&header::Accept(ref items)) if !items.is_empty() => Some(items[0].item.clone()),
+            _ => None
+        };
+    }
+
+    match accept {
+        Some(&header::Accept(ref items)) if !items.is_empty() => {
+            let mut winner: Option<(&Mime, u16)> = None;
+
+            for candidate in produces.iter() {
+                let quality = match best_match(items.as_slice(), candidate) {
+                    Some(quality_item) => quality_item.quality.0,
+                    None => continue
+                };
+
+                if quality == 0 {
+                    continue;
+                }
+
+                let better = match winner {
+                    Some((_, best_quality)) => quality > best_quality,
+                    None => true
+                };
+
+                if better {
+                    winner = Some((candidate, quality));
+                }
+            }
+
+            winner.map(|(candidate, _)| candidate.clone())
+        },
+        // No Accept header at all means "anything goes".
+        _ => Some(produces[0].clone())
+    }
+}
+
+/// Is `content_type` one of the mime types `consumes` declares acceptable?
+pub fn validate_consumes(content_type: &Mime, consumes: &[Mime]) -> bool {
+    if consumes.is_empty() {
+        return true;
+    }
+
+    consumes.iter().any(|candidate| mime_matches(content_type, candidate))
+}
+
+#[cfg(test)]
+mod tests {
+    use server::mime::{Mime, TopLevel, SubLevel};
+    use server::header::{Accept, Quality, QualityItem};
+
+    use super::negotiate_produces;
+
+    fn mime(top: TopLevel, sub: SubLevel) -> Mime {
+        Mime(top, sub, vec![])
+    }
+
+    fn qitem(m: Mime, q: u16) -> QualityItem<Mime> {
+        QualityItem { item: m, quality: Quality(q) }
+    }
+
+    #[test]
+    fn exact_q_zero_rejects_even_with_a_higher_q_wildcard() {
+        let json = mime(TopLevel::Application, SubLevel::Json);
+        let any = mime(TopLevel::Star, SubLevel::Star);
+
+        let accept = Accept(vec![qitem(json.clone(), 0), qitem(any, 500)]);
+
+        assert!(negotiate_produces(Some(&accept), &[json]).is_none());
+    }
+
+    #[test]
+    fn no_accept_header_picks_first_declared_produces_entry() {
+        let json = mime(TopLevel::Application, SubLevel::Json);
+        let xml = mime(TopLevel::Application, SubLevel::Xml);
+
+        assert_eq!(negotiate_produces(None, &[json.clone(), xml]), Some(json));
+    }
+}